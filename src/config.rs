@@ -0,0 +1,93 @@
+use serde::Deserialize;
+use std::{fs, path::PathBuf, time::Duration};
+
+use crate::TimestampFormat;
+
+/// User-supplied defaults and poll bounds, loaded from `config.toml`.
+///
+/// Every field is optional: a missing config file (or a missing field within
+/// it) just means the CLI falls back to its built-in defaults.
+#[derive(Debug, Default, Deserialize)]
+pub struct Config {
+    pub timestamp_format: Option<TimestampFormat>,
+    pub interval: Option<u64>,
+    pub max_errors_in_row: Option<usize>,
+    #[serde(default, with = "duration_format::option")]
+    pub max_duration: Option<Duration>,
+}
+
+/// Searches `$XDG_CONFIG_HOME/ec2im/config.toml` and
+/// `~/.config/ec2im/config.toml`, in that order.
+///
+/// Returns the first path that exists, or every path that was checked if
+/// none did.
+pub fn get_config_path() -> Result<PathBuf, Vec<PathBuf>> {
+    let mut attempted = Vec::new();
+
+    if let Some(xdg) = std::env::var_os("XDG_CONFIG_HOME") {
+        let path = PathBuf::from(xdg).join("ec2im/config.toml");
+        if path.is_file() {
+            return Ok(path);
+        }
+        attempted.push(path);
+    }
+
+    if let Some(home) = std::env::var_os("HOME") {
+        let path = PathBuf::from(home).join(".config/ec2im/config.toml");
+        if path.is_file() {
+            return Ok(path);
+        }
+        attempted.push(path);
+    }
+
+    Err(attempted)
+}
+
+/// Loads the config file, falling back to `Config::default()` if none is
+/// found or it can't be parsed.
+pub fn read_config() -> Config {
+    let Ok(path) = get_config_path() else {
+        return Config::default();
+    };
+
+    match fs::read_to_string(&path) {
+        Ok(contents) => toml::from_str(&contents).unwrap_or_default(),
+        Err(_) => Config::default(),
+    }
+}
+
+/// Parses durations like `"30s"`, `"5m"`, `"2h"` into `std::time::Duration`.
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    let split_at = s
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(|| format!("invalid duration: {s}"))?;
+    let (num, unit) = s.split_at(split_at);
+    let num: u64 = num.parse().map_err(|_| format!("invalid duration: {s}"))?;
+
+    let overflow_err = || format!("duration too large: {s}");
+    let secs = match unit {
+        "s" => num,
+        "m" => num.checked_mul(60).ok_or_else(overflow_err)?,
+        "h" => num.checked_mul(3600).ok_or_else(overflow_err)?,
+        _ => return Err(format!("unknown duration unit in: {s}")),
+    };
+
+    Ok(Duration::from_secs(secs))
+}
+
+mod duration_format {
+    pub mod option {
+        use serde::{Deserialize, Deserializer};
+        use std::time::Duration;
+
+        pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<Duration>, D::Error>
+        where
+            D: Deserializer<'de>,
+        {
+            let raw: Option<String> = Option::deserialize(deserializer)?;
+            raw.map(|s| super::super::parse_duration(&s).map_err(serde::de::Error::custom))
+                .transpose()
+        }
+    }
+}
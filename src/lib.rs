@@ -0,0 +1,367 @@
+pub mod config;
+
+use chrono::Utc;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::VecDeque,
+    error::Error,
+    sync::{mpsc, mpsc::RecvTimeoutError, Arc, RwLock},
+    thread,
+    time::{Duration, Instant},
+};
+
+const ENDPOINT: &str = "http://169.254.169.254";
+const TOKEN_TTL: Duration = Duration::from_secs(21600);
+const TOKEN_REFRESH_OFFSET: Duration = Duration::from_secs(10800);
+const CONNECT_TIMEOUT_SECS: u64 = 2;
+
+pub const DEFAULT_TIMESTAMP_FORMAT: TimestampFormat = TimestampFormat::Iso;
+pub const DEFAULT_INTERVAL_MS: u64 = 5000;
+pub const MIN_INTERVAL_MS: u64 = 500;
+pub const MAX_INTERVAL_MS: u64 = 10000;
+
+#[derive(ValueEnum, Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum TimestampFormat {
+    Iso,
+    Unix,
+}
+
+pub struct GlobalConfig {
+    timestamp_format: TimestampFormat,
+}
+
+impl GlobalConfig {
+    pub fn new(timestamp_format: TimestampFormat) -> Self {
+        Self { timestamp_format }
+    }
+}
+
+fn get_token() -> Result<String, Box<dyn Error>> {
+    let response = minreq::put(format!("{}/latest/api/token", ENDPOINT))
+        .with_header("X-aws-ec2-metadata-token-ttl-seconds", TOKEN_TTL.as_secs().to_string())
+        .with_timeout(CONNECT_TIMEOUT_SECS)
+        .send()?;
+
+    if response.status_code == 200 {
+        Ok(response.as_str()?.trim().to_string())
+    } else {
+        Err(format!("Failed to get token (status {}).", response.status_code).into())
+    }
+}
+
+fn query(token: &str, path: &str) -> Result<String, Box<dyn Error>> {
+    let url = format!("{}/latest/{}", ENDPOINT, path);
+    let response = minreq::get(&url)
+        .with_header("X-aws-ec2-metadata-token", token)
+        .with_timeout(CONNECT_TIMEOUT_SECS)
+        .send()?;
+
+    match response.status_code {
+        200 => Ok(response.as_str()?.trim().to_string()),
+        401 => Err(format!("Token expired or invalid for {path} (401).").into()),
+        404 => Err(format!("No metadata found at {path} (404).").into()),
+        code => Err(format!("Failed to get data for {path} (status {code}).").into()),
+    }
+}
+
+/// Owns the IMDSv2 token lifecycle so callers don't have to juggle it
+/// themselves. A single client is meant to be reused across many `get`
+/// calls and, via `poll`, across a long-running loop.
+pub struct MetadataClient {
+    token: Arc<RwLock<String>>,
+    token_obtained_at: Arc<RwLock<Instant>>,
+}
+
+impl MetadataClient {
+    /// Fetches an initial token and returns a client ready to query paths.
+    pub fn new() -> Result<Self, Box<dyn Error>> {
+        let token = get_token()?;
+        Ok(Self {
+            token: Arc::new(RwLock::new(token)),
+            token_obtained_at: Arc::new(RwLock::new(Instant::now())),
+        })
+    }
+
+    /// Fetches a fresh token and swaps it in, for use before the current one expires.
+    pub fn refresh_token(&self) -> Result<(), Box<dyn Error>> {
+        let new_token = get_token()?;
+        *self.token.write().unwrap() = new_token;
+        *self.token_obtained_at.write().unwrap() = Instant::now();
+        Ok(())
+    }
+
+    /// Queries a single metadata path using the currently held token.
+    pub fn get(&self, path: &str) -> Result<String, Box<dyn Error>> {
+        let token = self.token.read().unwrap().clone();
+        query(&token, path)
+    }
+
+    /// Queries every path in `paths` on each `interval_ms` tick, refreshing
+    /// the token on a background thread, until `max_errors_in_row`
+    /// consecutive failing ticks or `max_duration` of wall-clock time
+    /// elapses (if set). Each tick drains its own queue of paths so one
+    /// slow/failing path doesn't stop the rest from being sampled.
+    ///
+    /// The refresh thread reports freshly obtained tokens back over an
+    /// `mpsc` channel rather than sharing `self.token`, and a Ctrl-C/SIGTERM
+    /// handler signals both the refresh thread and this loop to stop. On
+    /// shutdown, the refresh thread is joined and any output already
+    /// queued for the current tick is flushed before returning `Ok(())`.
+    pub fn poll(
+        &self,
+        paths: &[String],
+        interval_ms: u64,
+        config: &GlobalConfig,
+        raw: bool,
+        max_errors_in_row: Option<usize>,
+        max_duration: Option<Duration>,
+    ) -> Result<(), Box<dyn Error>> {
+        let interval = Duration::from_millis(interval_ms);
+
+        let (token_tx, token_rx) = mpsc::channel::<String>();
+        let (refresh_stop_tx, refresh_stop_rx) = mpsc::channel::<()>();
+
+        let mut current_token = self.token.read().unwrap().clone();
+
+        let refresh_handle = thread::spawn(move || {
+            let mut token_obtained_at = Instant::now();
+            loop {
+                let refresh_interval = TOKEN_TTL - TOKEN_REFRESH_OFFSET;
+                let sleep_until = token_obtained_at + refresh_interval;
+                let wait = sleep_until.saturating_duration_since(Instant::now());
+                if refresh_stop_rx.recv_timeout(wait) != Err(RecvTimeoutError::Timeout) {
+                    return;
+                }
+
+                loop {
+                    if let Ok(new_token) = get_token() {
+                        token_obtained_at = Instant::now();
+                        if token_tx.send(new_token).is_err() {
+                            return;
+                        }
+                        break;
+                    }
+                    if refresh_stop_rx.recv_timeout(Duration::from_secs(60)) != Err(RecvTimeoutError::Timeout) {
+                        return;
+                    }
+                }
+            }
+        });
+
+        let (shutdown_tx, shutdown_rx) = mpsc::channel::<()>();
+        ctrlc::set_handler(move || {
+            let _ = shutdown_tx.send(());
+        })?;
+
+        let started_at = Instant::now();
+        let mut errors_in_row = 0;
+
+        loop {
+            if let Ok(new_token) = token_rx.try_recv() {
+                current_token = new_token;
+            }
+
+            let mut request_queue: VecDeque<&String> = paths.iter().collect();
+            let mut tick_had_error = false;
+
+            while let Some(path) = request_queue.pop_front() {
+                let result = query(&current_token, path);
+                if result.is_err() {
+                    tick_had_error = true;
+                }
+                println!("{}", to_json(path, result, config, raw));
+            }
+
+            errors_in_row = if tick_had_error { errors_in_row + 1 } else { 0 };
+
+            let bound_exceeded = max_errors_in_row.is_some_and(|max| errors_in_row >= max)
+                || max_duration.is_some_and(|max| started_at.elapsed() >= max);
+
+            let shutdown_requested = bound_exceeded
+                || !matches!(shutdown_rx.recv_timeout(interval), Err(RecvTimeoutError::Timeout));
+
+            if shutdown_requested {
+                let _ = refresh_stop_tx.send(());
+                let _ = refresh_handle.join();
+                return Ok(());
+            }
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+struct InstanceIdentityDocument {
+    #[serde(rename(deserialize = "instanceId"))]
+    instance_id: String,
+    region: String,
+    #[serde(rename(deserialize = "instanceType"))]
+    instance_type: String,
+    #[serde(rename(deserialize = "availabilityZone"))]
+    availability_zone: String,
+    #[serde(rename(deserialize = "pendingTime"))]
+    pending_time: String,
+}
+
+#[derive(Deserialize, Serialize)]
+struct IamInfo {
+    #[serde(rename(deserialize = "Code"))]
+    code: String,
+    #[serde(rename(deserialize = "LastUpdated"))]
+    last_updated: String,
+    #[serde(rename(deserialize = "InstanceProfileArn"))]
+    instance_profile_arn: String,
+    #[serde(rename(deserialize = "InstanceProfileId"))]
+    instance_profile_id: String,
+}
+
+/// Attempts to parse `body` into a known typed shape for `path`, falling back
+/// to the raw string when the path isn't recognized or parsing fails.
+fn parse_value(path: &str, body: &str, raw: bool) -> Value {
+    if raw {
+        return Value::String(body.to_string());
+    }
+
+    let typed = match path {
+        "dynamic/instance-identity/document" => serde_json::from_str::<InstanceIdentityDocument>(body)
+            .ok()
+            .and_then(|doc| serde_json::to_value(doc).ok()),
+        "meta-data/iam/info" => serde_json::from_str::<IamInfo>(body)
+            .ok()
+            .and_then(|info| serde_json::to_value(info).ok()),
+        _ => None,
+    };
+
+    typed.unwrap_or_else(|| Value::String(body.to_string()))
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+pub enum Timestamp {
+    Iso(String),
+    Unix(i64),
+}
+
+#[derive(Serialize)]
+pub struct Output {
+    path: String,
+    timestamp: Timestamp,
+    #[serde(flatten)]
+    result: QueryResult,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "status", rename_all = "lowercase")]
+pub enum QueryResult {
+    Success {
+        value: Value,
+    },
+    Error {
+        value: Option<String>,
+        reason: String,
+    }
+}
+
+pub fn to_json(path: &str, res: Result<String, Box<dyn Error>>, config: &GlobalConfig, raw: bool) -> String {
+    let timestamp = match config.timestamp_format {
+        TimestampFormat::Iso => Timestamp::Iso(
+            Utc::now().format("%Y-%m-%dT%H:%M:%S%.3f").to_string()
+        ),
+        TimestampFormat::Unix => Timestamp::Unix(Utc::now().timestamp_millis()),
+    };
+    let query_result = match res {
+        Ok(v) => QueryResult::Success { value: parse_value(path, &v, raw) },
+        Err(e) => QueryResult::Error { value: None, reason: e.to_string() },
+    };
+    let output = Output {
+        path: path.to_string(),
+        timestamp,
+        result: query_result,
+    };
+
+    serde_json::to_string(&output).unwrap_or_else(|_| "{}".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_json_success_iso_timestamp() {
+        // given
+        let result = Ok("i-0b22a22eec53b9321".to_string());
+        let config = GlobalConfig::new(TimestampFormat::Iso);
+
+        // when
+        let res = to_json("meta-data/instance-id", result, &config, false);
+
+        // then
+        let ser_res: Value = serde_json::from_str(&res).expect("valid json");
+        assert_eq!(ser_res["status"], "success");
+        assert_eq!(ser_res["value"], "i-0b22a22eec53b9321");
+        assert!(ser_res["timestamp"].is_string());
+    }
+
+    #[test]
+    fn test_to_json_success_unix_timestamp() {
+        // given
+        let result = Ok("i-0b22a22eec53b9321".to_string());
+        let config = GlobalConfig::new(TimestampFormat::Unix);
+
+        // when
+        let res = to_json("meta-data/instance-id", result, &config, false);
+
+        // then
+        let ser_res: Value = serde_json::from_str(&res).expect("valid json");
+        assert_eq!(ser_res["status"], "success");
+        assert_eq!(ser_res["value"], "i-0b22a22eec53b9321");
+        assert!(ser_res["timestamp"].is_number());
+    }
+
+    #[test]
+    fn test_to_json_error() {
+        // given
+        let result = Err("connection timeout".into());
+        let config = GlobalConfig::new(TimestampFormat::Unix);
+
+        // when
+        let res = to_json("meta-data/instance-id", result, &config, false);
+
+        // then
+        let ser_res: Value = serde_json::from_str(&res).expect("valid json");
+        assert_eq!(ser_res["value"], Value::Null);
+    }
+
+    #[test]
+    fn test_to_json_typed_instance_identity_document() {
+        // given
+        let body = r#"{"instanceId":"i-0b22a22eec53b9321","region":"us-east-1","instanceType":"t3.micro","availabilityZone":"us-east-1a","pendingTime":"2021-01-01T00:00:00Z"}"#;
+        let result = Ok(body.to_string());
+        let config = GlobalConfig::new(TimestampFormat::Iso);
+
+        // when
+        let res = to_json("dynamic/instance-identity/document", result, &config, false);
+
+        // then
+        let ser_res: Value = serde_json::from_str(&res).expect("valid json");
+        assert_eq!(ser_res["value"]["instance_id"], "i-0b22a22eec53b9321");
+        assert_eq!(ser_res["value"]["region"], "us-east-1");
+    }
+
+    #[test]
+    fn test_to_json_raw_flag_forces_string_value() {
+        // given
+        let body = r#"{"instanceId":"i-0b22a22eec53b9321","region":"us-east-1","instanceType":"t3.micro","availabilityZone":"us-east-1a","pendingTime":"2021-01-01T00:00:00Z"}"#;
+        let result = Ok(body.to_string());
+        let config = GlobalConfig::new(TimestampFormat::Iso);
+
+        // when
+        let res = to_json("dynamic/instance-identity/document", result, &config, true);
+
+        // then
+        let ser_res: Value = serde_json::from_str(&res).expect("valid json");
+        assert_eq!(ser_res["value"], body);
+    }
+}
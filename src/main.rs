@@ -1,21 +1,17 @@
-use chrono::Utc;
-use clap::{Parser, Subcommand, ValueEnum};
-use serde::Serialize;
-use std::{
-    error::Error, sync::{Arc, RwLock}, thread, time::{Duration, Instant},
+use clap::{Parser, Subcommand};
+use ec2im::{
+    config, to_json, GlobalConfig, MetadataClient, TimestampFormat, DEFAULT_INTERVAL_MS,
+    DEFAULT_TIMESTAMP_FORMAT, MAX_INTERVAL_MS, MIN_INTERVAL_MS,
 };
-
-const ENDPOINT: &str = "http://169.254.169.254";
-const TOKEN_TTL: Duration = Duration::from_secs(21600);
-const TOKEN_REFRESH_OFFSET: Duration = Duration::from_secs(10800);
+use std::{error::Error, time::Duration};
 
 #[derive(Parser)]
 #[command(name = "ec2im", about = "EC2 Instance Metadata CLI")]
 struct Cli {
     #[command(subcommand)]
     command: Command,
-    #[arg(long, short = 't', value_enum, default_value_t = TimestampFormat::Iso, global = true)]
-    timestamp_format: TimestampFormat,
+    #[arg(long, short = 't', value_enum, global = true, help = "Defaults to the config file's value, then \"iso\"")]
+    timestamp_format: Option<TimestampFormat>,
 }
 
 #[derive(Subcommand)]
@@ -23,210 +19,57 @@ enum Command {
     #[command(about = "Get instance metadata once")]
     Get {
         path: String,
+        #[arg(long, help = "Always emit the raw response body as a string, skipping typed parsing")]
+        raw: bool,
     },
     #[command(about = "Get instance metadata periodically")]
     Poll {
-        path: String,
-        #[arg(long, short = 'i', default_value_t = 5000, value_parser = clap::value_parser!(u64).range(500..=10000))]
-        interval: u64,
+        #[arg(required = true, num_args = 1.., help = "One or more metadata paths, sampled once per tick")]
+        paths: Vec<String>,
+        #[arg(long, short = 'i', value_parser = clap::value_parser!(u64).range(500..=10000), help = "Defaults to the config file's value, then 5000ms")]
+        interval: Option<u64>,
+        #[arg(long, help = "Always emit the raw response body as a string, skipping typed parsing")]
+        raw: bool,
+        #[arg(long, help = "Stop polling after this many consecutive ticks in which any path failed")]
+        max_errors_in_row: Option<usize>,
+        #[arg(long, value_parser = parse_duration_arg, help = "Stop polling after this much wall-clock time, e.g. \"30m\"")]
+        max_duration: Option<Duration>,
     },
 }
 
-#[derive(ValueEnum, Clone, Debug)]
-enum TimestampFormat {
-    Iso,
-    Unix,
-}
-
-struct GlobalConfig {
-    timestamp_format: TimestampFormat,
-}
-
-impl GlobalConfig {
-    fn new(timestamp_format: TimestampFormat) -> Self {
-        Self { timestamp_format }
-    }
-}
-
-fn get_token() -> Result<String, Box<dyn Error>> {
-    let output = std::process::Command::new("curl")
-        .arg("--max-time")
-        .arg("2")
-        .arg("-X")
-        .arg("PUT")
-        .arg(format!("{}/latest/api/token", ENDPOINT))
-        .arg("-H")
-        .arg(format!("X-aws-ec2-metadata-token-ttl-seconds: {}", TOKEN_TTL.as_secs()))
-        .output()?;
-
-    if output.status.success() {
-        Ok(String::from_utf8(output.stdout)?.trim().to_string())
-    } else {
-        Err("Failed to get token.".into())
-    }
-}
-
-fn query(token: &str, path: &str) -> Result<String, Box<dyn Error>> {
-    let url = format!("{}/latest/{}", ENDPOINT, path);
-    let output = std::process::Command::new("curl")
-        .arg("-H")
-        .arg(format!("X-aws-ec2-metadata-token: {}", token))
-        .arg(&url)
-        .output()?;
-
-    if output.status.success() {
-        Ok(String::from_utf8(output.stdout)?.trim().to_string())
-    } else {
-        Err(format!("Failed to get data for {path}.").into())
-    }
-}
-
-fn poll(init_token: String, path: &str, interval_ms: u64, config: &GlobalConfig) -> Result<(), Box<dyn Error>> {
-    let interval = Duration::from_millis(interval_ms);
-    let token = Arc::new(RwLock::new(init_token));
-    let token_obtained_at = Arc::new(RwLock::new(Instant::now()));
-
-    let token_clone = Arc::clone(&token);
-    let token_obtained_at_clone = Arc::clone(&token_obtained_at);
-
-    thread::spawn(move || {
-        loop {
-            let refresh_interval = TOKEN_TTL - TOKEN_REFRESH_OFFSET;
-            let sleep_until = *token_obtained_at_clone.read().unwrap() + refresh_interval;
-            let now = Instant::now();
-            if sleep_until > now {
-                thread::sleep(sleep_until - now);
-            }
-            loop {
-                if let Ok(new_token) = get_token() {
-                    *token_clone.write().unwrap() = new_token;
-                    *token_obtained_at_clone.write().unwrap() = Instant::now();
-                    break;
-                }
-                thread::sleep(Duration::from_secs(60));
-            }
-        }
-    });
-
-    loop {
-        let current_token = token.read().unwrap().clone();
-        println!("{}", to_json(query(&current_token, path), &config));
-        thread::sleep(interval);
-    }
-}
-
-#[derive(Serialize)]
-#[serde(untagged)]
-enum Timestamp {
-    Iso(String),
-    Unix(i64),
-}
-
-#[derive(Serialize)]
-struct Output {
-    timestamp: Timestamp,
-    #[serde(flatten)]
-    result: QueryResult,
-}
-
-#[derive(Serialize)]
-#[serde(tag = "status", rename_all = "lowercase")]
-enum QueryResult {
-    Success {
-        value: String,
-    },
-    Error {
-        value: Option<String>,
-        reason: String,
-    }
-}
-
-fn to_json(res: Result<String, Box<dyn Error>>, config: &GlobalConfig) -> String {
-    let timestamp = match config.timestamp_format {
-        TimestampFormat::Iso => Timestamp::Iso(
-            Utc::now().format("%Y-%m-%dT%H:%M:%S%.3f").to_string()
-        ),
-        TimestampFormat::Unix => Timestamp::Unix(Utc::now().timestamp_millis()),
-    };
-    let query_result = match res {
-        Ok(v) => QueryResult::Success { value: v },
-        Err(e) => QueryResult::Error { value: None, reason: e.to_string() },
-    };
-    let output = Output {
-        timestamp,
-        result: query_result,
-    };
-
-    serde_json::to_string(&output).unwrap_or_else(|_| "{}".to_string())
+fn parse_duration_arg(s: &str) -> Result<Duration, String> {
+    config::parse_duration(s)
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
     let cli = Cli::parse();
-    let config = GlobalConfig::new(cli.timestamp_format);
-    let token = get_token()?;
+    let file_config = config::read_config();
+    let global_config = GlobalConfig::new(
+        cli.timestamp_format
+            .or(file_config.timestamp_format)
+            .unwrap_or(DEFAULT_TIMESTAMP_FORMAT),
+    );
+    let client = MetadataClient::new()?;
 
     match cli.command {
-        Command::Get { path } => {
+        Command::Get { path, raw } => {
             println!("Querying {path}...");
-            println!("{}", to_json(query(&token, &path), &config));
+            println!("{}", to_json(&path, client.get(&path), &global_config, raw));
         },
-        Command::Poll { path, interval } => {
-            println!("Polling {path}...");
-            poll(token, &path, interval, &config)?;
+        Command::Poll { paths, interval, raw, max_errors_in_row, max_duration } => {
+            let interval = interval.or(file_config.interval).unwrap_or(DEFAULT_INTERVAL_MS);
+            if !(MIN_INTERVAL_MS..=MAX_INTERVAL_MS).contains(&interval) {
+                return Err(format!(
+                    "interval must be between {MIN_INTERVAL_MS} and {MAX_INTERVAL_MS}ms, got {interval}ms (check your config file)"
+                ).into());
+            }
+            let max_errors_in_row = max_errors_in_row.or(file_config.max_errors_in_row);
+            let max_duration = max_duration.or(file_config.max_duration);
+
+            println!("Polling {}...", paths.join(", "));
+            client.poll(&paths, interval, &global_config, raw, max_errors_in_row, max_duration)?;
         }
     }
 
     Ok(())
 }
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use serde_json::Value;
-
-    #[test]
-    fn test_to_json_success_iso_timestamp() {
-        // given
-        let result = Ok("i-0b22a22eec53b9321".to_string());
-        let config = GlobalConfig::new(TimestampFormat::Iso);
-
-        // when
-        let res = to_json(result, &config);
-
-        // then
-        let ser_res: Value = serde_json::from_str(&res).expect("valid json");
-        assert_eq!(ser_res["status"], "success");
-        assert_eq!(ser_res["value"], "i-0b22a22eec53b9321");
-        assert!(ser_res["timestamp"].is_string());
-    }
-
-    #[test]
-    fn test_to_json_success_unix_timestamp() {
-        // given
-        let result = Ok("i-0b22a22eec53b9321".to_string());
-        let config = GlobalConfig::new(TimestampFormat::Unix);
-
-        // when
-        let res = to_json(result, &config);
-
-        // then
-        let ser_res: Value = serde_json::from_str(&res).expect("valid json");
-        assert_eq!(ser_res["status"], "success");
-        assert_eq!(ser_res["value"], "i-0b22a22eec53b9321");
-        assert!(ser_res["timestamp"].is_number());
-    }
-
-    #[test]
-    fn test_to_json_error() {
-        // given
-        let result = Err("connection timeout".into());
-        let config = GlobalConfig::new(TimestampFormat::Unix);
-
-        // when
-        let res = to_json(result, &config);
-
-        // then
-        let ser_res: Value = serde_json::from_str(&res).expect("valid json");
-        assert_eq!(ser_res["value"], Value::Null);
-    }
-}